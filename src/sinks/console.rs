@@ -38,6 +38,15 @@ pub struct ConsoleSinkConfig {
 #[typetag::serde(name = "console")]
 impl SinkConfig for ConsoleSinkConfig {
     fn build(&self, acker: Acker) -> Result<(super::RouterSink, super::Healthcheck), String> {
+        if let Some(encoding) = &self.encoding {
+            if encoding.is_binary() {
+                return Err(format!(
+                    "the console sink writes newline-framed text and cannot use the {:?} encoding",
+                    encoding
+                ));
+            }
+        }
+
         let encoding = self.encoding.clone();
 
         let output: Box<dyn io::AsyncWrite + Send> = match self.target {
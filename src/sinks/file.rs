@@ -10,19 +10,320 @@ use crate::{
 
 use bytes::Bytes;
 use serde::{Deserialize, Serialize};
+use std::io;
+use std::io::Write;
+use std::mem;
 use std::path::PathBuf;
 
 use futures::{future, try_ready, Async, AsyncSink, Future, Poll, Sink, StartSend};
 use tokio::codec::{BytesCodec, FramedWrite};
-use tokio::fs::file::{CreateFuture, File};
+use tokio::fs::file::{File, OpenFuture};
+use tokio_threadpool::blocking;
+
+use flate2::write::{GzEncoder, ZlibEncoder};
+use flate2::Compression as FlateLevel;
 
 use tracing::field;
 
+/// Codecs used to losslessly shrink encoded events on their way to disk.
+///
+/// `Gzip`, `Zlib` and `Brotli` are all stateful, single-stream formats: only
+/// `Gzip` defines concatenation of independently-finished members as valid
+/// input to a streaming decoder. So rather than finish a fresh frame per
+/// flushed batch, a `FileSink` keeps one encoder alive for the lifetime of
+/// the file and only writes the trailer once, when the sink closes, so each
+/// opening of the file produces one complete stream. Files are opened for
+/// append, so a file written again after it was closed (e.g. an idle
+/// `PartitionedFileSink` partition that gets reopened) holds one stream per
+/// opening, which only `Gzip` decoders read as a whole.
+#[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    None,
+    Gzip,
+    Zlib,
+    Brotli,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression::None
+    }
+}
+
+// Buffers smaller than this are compressed inline rather than dispatched to
+// the blocking pool, since the cost of hopping threads would dwarf the cost
+// of compressing a handful of events.
+const INLINE_COMPRESS_THRESHOLD: usize = 2048;
+
+// How much encoded-but-not-yet-compressed data `start_send` will buffer
+// before applying backpressure. Without a bound, a producer that never
+// stops sending would grow `pending` without limit.
+const MAX_PENDING_BYTES: usize = 64 * 1024;
+
+/// The live encoder backing a compressed `FileSink`. One of these is created
+/// per file and lives until the sink is closed or dropped, so the stream it
+/// produces is a single continuous frame rather than one per flushed batch.
+enum ActiveEncoder {
+    Gzip(GzEncoder<Vec<u8>>),
+    Zlib(ZlibEncoder<Vec<u8>>),
+    Brotli(Box<brotli::CompressorWriter<Vec<u8>>>),
+}
+
+impl ActiveEncoder {
+    fn new(compression: Compression) -> Option<Self> {
+        match compression {
+            Compression::None => None,
+            Compression::Gzip => Some(ActiveEncoder::Gzip(GzEncoder::new(
+                Vec::new(),
+                FlateLevel::default(),
+            ))),
+            Compression::Zlib => Some(ActiveEncoder::Zlib(ZlibEncoder::new(
+                Vec::new(),
+                FlateLevel::default(),
+            ))),
+            Compression::Brotli => Some(ActiveEncoder::Brotli(Box::new(
+                brotli::CompressorWriter::new(Vec::new(), 4096, 5, 22),
+            ))),
+        }
+    }
+
+    // Feeds `data` into the encoder and forces out whatever compressed bytes
+    // are ready, without ending the stream - later batches land in the same
+    // continuous frame.
+    fn compress(&mut self, data: &[u8]) -> Vec<u8> {
+        match self {
+            ActiveEncoder::Gzip(encoder) => {
+                encoder
+                    .write_all(data)
+                    .expect("writing to an in-memory buffer cannot fail");
+                encoder
+                    .flush()
+                    .expect("writing to an in-memory buffer cannot fail");
+                mem::replace(encoder.get_mut(), Vec::new())
+            }
+            ActiveEncoder::Zlib(encoder) => {
+                encoder
+                    .write_all(data)
+                    .expect("writing to an in-memory buffer cannot fail");
+                encoder
+                    .flush()
+                    .expect("writing to an in-memory buffer cannot fail");
+                mem::replace(encoder.get_mut(), Vec::new())
+            }
+            ActiveEncoder::Brotli(encoder) => {
+                encoder
+                    .write_all(data)
+                    .expect("writing to an in-memory buffer cannot fail");
+                encoder
+                    .flush()
+                    .expect("writing to an in-memory buffer cannot fail");
+                mem::replace(encoder.get_mut(), Vec::new())
+            }
+        }
+    }
+
+    // Writes the trailer and returns whatever bytes were still buffered.
+    // Only called once, when the file is closing.
+    fn finish(self) -> Vec<u8> {
+        match self {
+            ActiveEncoder::Gzip(encoder) => encoder
+                .finish()
+                .expect("writing to an in-memory buffer cannot fail"),
+            ActiveEncoder::Zlib(encoder) => encoder
+                .finish()
+                .expect("writing to an in-memory buffer cannot fail"),
+            ActiveEncoder::Brotli(mut encoder) => {
+                encoder
+                    .flush()
+                    .expect("writing to an in-memory buffer cannot fail");
+                encoder.into_inner()
+            }
+        }
+    }
+}
+
+fn spawn_compress_batch(
+    encoder: ActiveEncoder,
+    data: Vec<u8>,
+) -> impl Future<Item = (ActiveEncoder, Vec<u8>), Error = ()> + Send {
+    let mut encoder = Some(encoder);
+    future::poll_fn(move || {
+        blocking(|| {
+            let mut encoder = encoder
+                .take()
+                .expect("compression job polled again after completing");
+            let out = encoder.compress(&data);
+            (encoder, out)
+        })
+        .map_err(|_| {
+            panic!("the threadpool shut down before the blocking compression task could run")
+        })
+    })
+}
+
+/// The storage layer a `FileSink` writes through. `TokioFsBackend` (the
+/// default, and the only one available off of Linux) goes through
+/// `tokio::fs`; the `io-uring` feature swaps in a ring-backed writer for
+/// lower per-write overhead on high-fanout workloads like
+/// `PartitionedFileSink`.
+pub trait FileBackend: Send + 'static {
+    type Handle: Sink<SinkItem = Bytes, SinkError = io::Error> + Send + 'static;
+    type CreateFuture: Future<Item = Self::Handle, Error = io::Error> + Send + 'static;
+
+    fn create(path: PathBuf) -> Self::CreateFuture;
+}
+
+fn open_framed(file: File) -> FramedWrite<File, BytesCodec> {
+    FramedWrite::new(file, BytesCodec::new())
+}
+
+pub struct TokioFsBackend;
+
+impl FileBackend for TokioFsBackend {
+    type Handle = FramedWrite<File, BytesCodec>;
+    type CreateFuture = future::Map<OpenFuture<PathBuf>, fn(File) -> FramedWrite<File, BytesCodec>>;
+
+    fn create(path: PathBuf) -> Self::CreateFuture {
+        // Partitions are repeatedly closed and reopened as they go idle and
+        // then active again, so opening with `File::create` (truncate on
+        // open) would wipe out everything already written on every reopen.
+        // Open for append instead: the file is still created if it doesn't
+        // exist yet, but existing contents are preserved.
+        tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map(open_framed as fn(File) -> FramedWrite<File, BytesCodec>)
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+mod uring {
+    use super::*;
+    use std::fs::OpenOptions;
+    use std::sync::Arc;
+
+    // Every file opened through this backend submits its writes on one
+    // shared ring, so a partitioned sink with many open files doesn't pay
+    // for a ring (and the kernel resources behind it) per file.
+    lazy_static::lazy_static! {
+        static ref RING: rio::Rio = rio::new().expect("failed to start io_uring");
+    }
+
+    pub struct IoUringBackend;
+
+    impl FileBackend for IoUringBackend {
+        type Handle = IoUringHandle;
+        type CreateFuture = Box<dyn Future<Item = Self::Handle, Error = io::Error> + Send>;
+
+        fn create(path: PathBuf) -> Self::CreateFuture {
+            Box::new(
+                future::poll_fn(move || {
+                    blocking(|| {
+                        // No `.truncate(true)`: partitions are repeatedly
+                        // closed and reopened as they go idle and active
+                        // again, so opening here must preserve whatever was
+                        // already written. Starting `offset` at the file's
+                        // current length (rather than 0) then keeps
+                        // subsequent writes appending instead of
+                        // overwriting it.
+                        let file = OpenOptions::new().create(true).write(true).open(&path)?;
+                        let offset = file.metadata()?.len();
+                        Ok((file, offset))
+                    })
+                    .map_err(|_| {
+                        panic!("the threadpool shut down before the file could be opened")
+                    })
+                })
+                .and_then(|opened: io::Result<(std::fs::File, u64)>| opened)
+                .map(|(file, offset)| IoUringHandle {
+                    file: Arc::new(file),
+                    offset,
+                    in_flight: None,
+                }),
+            )
+        }
+    }
+
+    pub struct IoUringHandle {
+        file: Arc<std::fs::File>,
+        offset: u64,
+        // Resolves to the offset just past the last byte of the pending
+        // item, once the whole buffer has actually landed on disk.
+        in_flight: Option<Box<dyn Future<Item = u64, Error = io::Error> + Send>>,
+    }
+
+    // `write_at` is only required to write *some* of the buffer per call and
+    // can return short, so a single call isn't enough to guarantee the whole
+    // item made it to disk. Loop, advancing both the slice and the file
+    // offset by exactly what was actually written each time.
+    fn write_all_at(file: &std::fs::File, item: &Bytes, mut offset: u64) -> io::Result<u64> {
+        let mut written = 0;
+        while written < item.len() {
+            let n = RING.write_at(file, &item[written..], offset).wait()?;
+            if n == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::WriteZero,
+                    "failed to write whole buffer",
+                ));
+            }
+            written += n;
+            offset += n as u64;
+        }
+        Ok(offset)
+    }
+
+    impl Sink for IoUringHandle {
+        type SinkItem = Bytes;
+        type SinkError = io::Error;
+
+        fn start_send(&mut self, item: Bytes) -> StartSend<Bytes, io::Error> {
+            if self.in_flight.is_some() {
+                return Ok(AsyncSink::NotReady(item));
+            }
+
+            let file = self.file.clone();
+            let offset = self.offset;
+
+            self.in_flight = Some(Box::new(
+                future::poll_fn(move || {
+                    blocking(|| write_all_at(&file, &item, offset)).map_err(|_| {
+                        panic!(
+                            "the threadpool shut down before the ring submission could complete"
+                        )
+                    })
+                })
+                .and_then(|written| written),
+            ));
+
+            Ok(AsyncSink::Ready)
+        }
+
+        fn poll_complete(&mut self) -> Poll<(), io::Error> {
+            if let Some(ref mut in_flight) = self.in_flight {
+                let new_offset = try_ready!(in_flight.poll());
+                self.offset = new_offset;
+            }
+            self.in_flight = None;
+            Ok(Async::Ready(()))
+        }
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+pub type DefaultBackend = uring::IoUringBackend;
+
+#[cfg(not(all(target_os = "linux", feature = "io-uring")))]
+pub type DefaultBackend = TokioFsBackend;
+
 #[derive(Deserialize, Serialize, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct FileSinkConfig {
     pub path: PathBuf,
     pub encoding: Option<BasicEncoding>,
+    #[serde(default)]
+    pub compression: Compression,
 }
 
 impl FileSinkConfig {
@@ -30,6 +331,7 @@ impl FileSinkConfig {
         Self {
             path,
             encoding: None,
+            compression: Compression::default(),
         }
     }
 }
@@ -39,7 +341,7 @@ impl crate::topology::config::SinkConfig for FileSinkConfig {
     fn build(&self, acker: Acker) -> Result<(super::RouterSink, super::Healthcheck), String> {
         let encoding = self.encoding.clone();
 
-        let sink = FileSink::new(self.path.clone())
+        let sink = FileSink::new_with_compression(self.path.clone(), self.compression)
             .stream_ack(acker)
             .with(move |event| encoding::log_event_as_bytes_with_nl(event, &encoding));
 
@@ -51,50 +353,78 @@ impl crate::topology::config::SinkConfig for FileSinkConfig {
     }
 }
 
-pub struct FileSink {
+pub struct FileSink<B: FileBackend = DefaultBackend> {
     pub path: PathBuf,
-    state: FileSinkState,
+    state: FileSinkState<B>,
+    compression: Compression,
+    // Encoded bytes waiting to be compressed into the next frame.
+    pending: Vec<u8>,
+    compressing: Option<CompressJob>,
+    // `Some` for the lifetime of the file whenever `compression` isn't
+    // `None`; taken out of `self` while a batch is being compressed on the
+    // blocking pool, and consumed for good in `Drop` to write the trailer.
+    encoder: Option<ActiveEncoder>,
+}
+
+enum CompressJob {
+    Ready(Bytes),
+    Pending(Box<dyn Future<Item = (ActiveEncoder, Vec<u8>), Error = ()> + Send>),
 }
 
-enum FileSinkState {
+enum FileSinkState<B: FileBackend> {
     Disconnected,
-    CreatingFile(CreateFuture<PathBuf>),
-    FileProvided(FramedWrite<File, BytesCodec>),
+    CreatingFile(B::CreateFuture),
+    FileProvided(B::Handle),
 }
 
-impl FileSinkState {
+impl<B: FileBackend> FileSinkState<B> {
     fn init(path: PathBuf) -> Self {
         debug!(message = "creating file", path = ?path.clone());
-        FileSinkState::CreatingFile(File::create(path))
+        FileSinkState::CreatingFile(B::create(path))
     }
 }
 
 pub type EmbeddedFileSink = Box<Sink<SinkItem = Event, SinkError = ()>>;
 
-impl FileSink {
+impl<B: FileBackend> FileSink<B> {
     pub fn new(path: PathBuf) -> Self {
+        Self::new_with_compression(path, Compression::None)
+    }
+
+    pub fn new_with_compression(path: PathBuf, compression: Compression) -> Self {
         Self {
             path: path.clone(),
             state: FileSinkState::init(path),
+            compression,
+            pending: Vec::new(),
+            compressing: None,
+            encoder: ActiveEncoder::new(compression),
         }
     }
 
     pub fn new_with_encoding(path: PathBuf, encoding: Option<BasicEncoding>) -> EmbeddedFileSink {
-        let sink = FileSink::new(path)
+        Self::new_with_encoding_and_compression(path, encoding, Compression::None)
+    }
+
+    pub fn new_with_encoding_and_compression(
+        path: PathBuf,
+        encoding: Option<BasicEncoding>,
+        compression: Compression,
+    ) -> EmbeddedFileSink {
+        let sink = FileSink::new_with_compression(path, compression)
             .with(move |event| encoding::log_event_as_bytes_with_nl(event, &encoding));
 
         Box::new(sink)
     }
 
-    pub fn poll_file(&mut self) -> Poll<&mut FramedWrite<File, BytesCodec>, ()> {
+    pub fn poll_file(&mut self) -> Poll<&mut B::Handle, ()> {
         loop {
             match self.state {
                 FileSinkState::Disconnected => return Err(()),
                 FileSinkState::CreatingFile(ref mut create_future) => match create_future.poll() {
-                    Ok(Async::Ready(file)) => {
-                        debug!(message = "created", file = ?file);
-                        self.state =
-                            FileSinkState::FileProvided(FramedWrite::new(file, BytesCodec::new()));
+                    Ok(Async::Ready(handle)) => {
+                        debug!(message = "created", path = ?self.path);
+                        self.state = FileSinkState::FileProvided(handle);
                     }
                     Ok(Async::NotReady) => return Ok(Async::NotReady),
                     Err(err) => {
@@ -104,46 +434,280 @@ impl FileSink {
                         return Err(());
                     }
                 },
-                FileSinkState::FileProvided(ref mut sink) => return Ok(Async::Ready(sink)),
+                FileSinkState::FileProvided(ref mut handle) => return Ok(Async::Ready(handle)),
+            }
+        }
+    }
+
+    // Drives any outstanding compression job and the write of its result to
+    // the underlying file, looping until there is nothing left to do or we
+    // have to report `NotReady`/an error to our caller.
+    fn poll_flush_compressed(&mut self) -> Poll<(), ()> {
+        loop {
+            if let Some(CompressJob::Pending(ref mut fut)) = self.compressing {
+                match fut.poll() {
+                    Ok(Async::Ready((encoder, compressed))) => {
+                        self.encoder = Some(encoder);
+                        self.compressing = Some(CompressJob::Ready(Bytes::from(compressed)));
+                    }
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Err(()) => {
+                        // The encoder was dropped along with the failed job,
+                        // so there's no way to keep extending the same
+                        // stream; treat this the same as any other
+                        // downstream write failure.
+                        error!("Error compressing batch for {:?}", self.path);
+                        self.state = FileSinkState::Disconnected;
+                        return Err(());
+                    }
+                }
+            }
+
+            // Don't take the compressed frame out of `self.compressing`
+            // until we know the file is ready to accept it - otherwise a
+            // `NotReady` from `poll_file` would drop the already-compressed
+            // bytes on the floor with no way to recompute them.
+            if let Some(CompressJob::Ready(_)) = self.compressing {
+                let file = try_ready!(self.poll_file());
+                let bytes = match self.compressing.take() {
+                    Some(CompressJob::Ready(bytes)) => bytes,
+                    _ => unreachable!(),
+                };
+                match file.start_send(bytes) {
+                    Ok(AsyncSink::Ready) => continue,
+                    Ok(AsyncSink::NotReady(bytes)) => {
+                        self.compressing = Some(CompressJob::Ready(bytes));
+                        return Ok(Async::NotReady);
+                    }
+                    Err(err) => {
+                        debug!(message = "disconnected", path = ?self.path);
+                        error!("Error while writing to {:?}: {}", self.path, err);
+                        self.state = FileSinkState::Disconnected;
+                        return Err(());
+                    }
+                }
             }
+
+            if !self.pending.is_empty() {
+                let batch = mem::replace(&mut self.pending, Vec::new());
+                let mut encoder = self
+                    .encoder
+                    .take()
+                    .expect("compressing FileSink is missing its encoder");
+                self.compressing = Some(if batch.len() < INLINE_COMPRESS_THRESHOLD {
+                    let out = encoder.compress(&batch);
+                    self.encoder = Some(encoder);
+                    CompressJob::Ready(Bytes::from(out))
+                } else {
+                    CompressJob::Pending(Box::new(spawn_compress_batch(encoder, batch)))
+                });
+                continue;
+            }
+
+            return Ok(Async::Ready(()));
         }
     }
 }
 
-impl Sink for FileSink {
-    type SinkItem = Bytes;
-    type SinkError = ();
+impl<B: FileBackend> Drop for FileSink<B> {
+    // Futures 0.1 has no async drop, so the sink's still-live file handle is
+    // handed off to a detached task that writes the trailer and flushes,
+    // rather than doing that I/O synchronously on whatever thread happens to
+    // be running the drop. Finalizing the encoder itself runs inline here
+    // when no batch is in flight, and otherwise once the in-flight
+    // compression job resolves.
+    fn drop(&mut self) {
+        let state = mem::replace(&mut self.state, FileSinkState::Disconnected);
+        let path = self.path.clone();
+        let pending = mem::replace(&mut self.pending, Vec::new());
 
-    fn start_send(&mut self, line: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
-        match self.poll_file() {
-            Ok(Async::Ready(file)) => {
-                debug!(
-                    message = "sending event",
-                    bytes = &field::display(line.len())
-                );
-                match file.start_send(line) {
+        if self.compression == Compression::None {
+            tokio::spawn(FinalizeWrite {
+                path,
+                state,
+                tail: Bytes::new(),
+            });
+            return;
+        }
+
+        match (self.encoder.take(), self.compressing.take()) {
+            // No batch was mid-flight: finalize right here and hand the
+            // trailer to the same handle every other write went through, so
+            // it lands after anything still buffered in that handle rather
+            // than racing it through a second fd.
+            (Some(encoder), ready) => {
+                let mut tail = match ready {
+                    Some(CompressJob::Ready(bytes)) => bytes.to_vec(),
+                    _ => Vec::new(),
+                };
+                tail.extend(finish_encoder(encoder, &pending));
+                tokio::spawn(FinalizeWrite {
+                    path,
+                    state,
+                    tail: Bytes::from(tail),
+                });
+            }
+            // A batch is still compressing on the blocking pool, which owns
+            // the encoder until that job resolves. Let it finish there
+            // instead of dropping it (and the trailer only it can produce)
+            // on the floor.
+            (None, Some(CompressJob::Pending(fut))) => {
+                tokio::spawn(fut.then(move |result| {
+                    let tail = match result {
+                        Ok((encoder, mut compressed)) => {
+                            compressed.extend(finish_encoder(encoder, &pending));
+                            compressed
+                        }
+                        Err(()) => Vec::new(),
+                    };
+                    FinalizeWrite {
+                        path,
+                        state,
+                        tail: Bytes::from(tail),
+                    }
+                }));
+            }
+            (None, _) => {}
+        }
+    }
+}
+
+// Feeds whatever was still buffered through the encoder and writes its
+// trailer, producing the bytes that still need to reach disk before the file
+// is fully closed out.
+fn finish_encoder(mut encoder: ActiveEncoder, pending: &[u8]) -> Vec<u8> {
+    let mut tail = if pending.is_empty() {
+        Vec::new()
+    } else {
+        encoder.compress(pending)
+    };
+    tail.extend(encoder.finish());
+    tail
+}
+
+// Drives a `FileSink`'s file handle to completion one last time after the
+// sink itself has been dropped: finishes creating the file if that was still
+// in flight, sends the trailer (if any) through it, and polls it to flush -
+// all on the same handle everything else was written through, so the
+// trailer can never land ahead of bytes still sitting in that handle's
+// buffer.
+struct FinalizeWrite<B: FileBackend> {
+    path: PathBuf,
+    state: FileSinkState<B>,
+    tail: Bytes,
+}
+
+impl<B: FileBackend> Future for FinalizeWrite<B> {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        loop {
+            match self.state {
+                FileSinkState::Disconnected => return Ok(Async::Ready(())),
+                FileSinkState::CreatingFile(ref mut create_future) => match create_future.poll() {
+                    Ok(Async::Ready(handle)) => self.state = FileSinkState::FileProvided(handle),
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
                     Err(err) => {
-                        debug!(
-                            message = "disconnected",
-                            path = ?self.path
+                        error!(
+                            "Error creating file {:?} while finalizing on drop: {}",
+                            self.path, err
                         );
-                        error!("Error while creating {:?}: {}", self.path, err);
-                        self.state = FileSinkState::Disconnected;
-                        Ok(AsyncSink::Ready)
+                        return Ok(Async::Ready(()));
+                    }
+                },
+                FileSinkState::FileProvided(ref mut handle) => {
+                    if !self.tail.is_empty() {
+                        match handle.start_send(mem::replace(&mut self.tail, Bytes::new())) {
+                            Ok(AsyncSink::Ready) => {}
+                            Ok(AsyncSink::NotReady(bytes)) => {
+                                self.tail = bytes;
+                                return Ok(Async::NotReady);
+                            }
+                            Err(err) => {
+                                error!(
+                                    "Error finalizing {:?} on drop: {}",
+                                    self.path, err
+                                );
+                                return Ok(Async::Ready(()));
+                            }
+                        }
                     }
-                    Ok(ok) => Ok(ok),
+
+                    return match handle.poll_complete() {
+                        Ok(ok) => Ok(ok),
+                        Err(err) => {
+                            error!(
+                                "Error flushing {:?} while finalizing on drop: {}",
+                                self.path, err
+                            );
+                            Ok(Async::Ready(()))
+                        }
+                    };
                 }
             }
-            Ok(Async::NotReady) => Ok(AsyncSink::NotReady(line)),
-            Err(_) => unreachable!(),
         }
     }
+}
+
+impl<B: FileBackend> Sink for FileSink<B> {
+    type SinkItem = Bytes;
+    type SinkError = ();
+
+    fn start_send(&mut self, line: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
+        if self.compression == Compression::None {
+            return match self.poll_file() {
+                Ok(Async::Ready(file)) => {
+                    debug!(
+                        message = "sending event",
+                        bytes = &field::display(line.len())
+                    );
+                    match file.start_send(line) {
+                        Err(err) => {
+                            debug!(
+                                message = "disconnected",
+                                path = ?self.path
+                            );
+                            error!("Error while creating {:?}: {}", self.path, err);
+                            self.state = FileSinkState::Disconnected;
+                            Ok(AsyncSink::Ready)
+                        }
+                        Ok(ok) => Ok(ok),
+                    }
+                }
+                Ok(Async::NotReady) => Ok(AsyncSink::NotReady(line)),
+                Err(_) => unreachable!(),
+            };
+        }
+
+        // Compression works on whole batches, so we just buffer here and let
+        // `poll_complete` do the (possibly pool-dispatched) compression
+        // work. Once the buffer is full we push back rather than grow it
+        // without bound - the caller will retry after driving us via
+        // `poll_complete`, which drains `pending` into the encoder.
+        // An empty buffer always takes the event, even one larger than the
+        // bound on its own, so an oversized event can't stall the sink.
+        if !self.pending.is_empty() && self.pending.len() + line.len() > MAX_PENDING_BYTES {
+            return Ok(AsyncSink::NotReady(line));
+        }
+
+        debug!(
+            message = "buffering event for compression",
+            bytes = &field::display(line.len())
+        );
+        self.pending.extend_from_slice(&line);
+        Ok(AsyncSink::Ready)
+    }
 
     fn poll_complete(&mut self) -> Result<Async<()>, Self::SinkError> {
         if let FileSinkState::Disconnected = self.state {
             return Err(());
         }
 
+        if self.compression != Compression::None {
+            try_ready!(self.poll_flush_compressed());
+        }
+
         let file = try_ready!(self.poll_file());
 
         match file.poll_complete() {
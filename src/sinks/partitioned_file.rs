@@ -1,7 +1,7 @@
 use crate::{
     buffers::Acker,
     event::Event,
-    sinks::file::{FileSink, EmbeddedFileSink},
+    sinks::file::{Compression, FileSink, EmbeddedFileSink},
     sinks::util::{
         encoding::{self, BasicEncoding},
         SinkExt,
@@ -10,11 +10,12 @@ use crate::{
     topology::config::DataType,
 };
 
-use futures::{future, try_ready, Async, AsyncSink, Future, Poll, Sink, StartSend};
+use futures::{future, Async, AsyncSink, Sink, StartSend, Stream};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::PathBuf;
-use tracing::field;
+use std::time::{Duration, Instant};
+use tokio::timer::Interval;
 
 #[derive(Deserialize, Serialize, Debug)]
 #[serde(deny_unknown_fields)]
@@ -23,6 +24,8 @@ pub struct PartitionedFileSinkConfig {
     #[serde(default = "default_close_timeout_secs")]
     pub close_timeout_secs: u64,
     pub encoding: Option<BasicEncoding>,
+    #[serde(default)]
+    pub compression: Compression,
 }
 
 fn default_close_timeout_secs() -> u64 {
@@ -35,6 +38,7 @@ impl PartitionedFileSinkConfig {
             path_template,
             close_timeout_secs : default_close_timeout_secs(),
             encoding: None,
+            compression: Compression::default(),
         }
     }
 }
@@ -42,8 +46,13 @@ impl PartitionedFileSinkConfig {
 #[typetag::serde(name = "partitioned_file")]
 impl crate::topology::config::SinkConfig for PartitionedFileSinkConfig {
     fn build(&self, acker: Acker) -> Result<(super::RouterSink, super::Healthcheck), String> {
-        let sink = PartitionedFileSink::new(Template::from(&self.path_template), self.encoding.clone())
-            .stream_ack(acker);
+        let sink = PartitionedFileSink::new(
+            Template::from(&self.path_template),
+            self.encoding.clone(),
+            self.compression,
+            Duration::from_secs(self.close_timeout_secs),
+        )
+        .stream_ack(acker);
 
         Ok((Box::new(sink), Box::new(future::ok(()))))
     }
@@ -53,19 +62,39 @@ impl crate::topology::config::SinkConfig for PartitionedFileSinkConfig {
     }
 }
 
+// A single partition's sink plus when it last saw an event, so idle
+// partitions can be identified and their file handles closed.
+struct Partition {
+    sink: EmbeddedFileSink,
+    last_used: Instant,
+}
+
 pub struct PartitionedFileSink {
     path_template: Template,
     encoding: Option<BasicEncoding>,
-    partitions: HashMap<PathBuf, EmbeddedFileSink>,
-    //todo: implement closing of files basing on timeout
+    compression: Compression,
+    close_timeout: Duration,
+    partitions: HashMap<PathBuf, Partition>,
+    // Ticks on `close_timeout`, independent of event traffic, so an idle
+    // partition still gets swept up by `poll_complete` even when nothing is
+    // arriving to drive it.
+    eviction_ticker: Interval,
 }
 
 impl PartitionedFileSink {
-    pub fn new(path_template: Template, encoding: Option<BasicEncoding>) -> Self {
+    pub fn new(
+        path_template: Template,
+        encoding: Option<BasicEncoding>,
+        compression: Compression,
+        close_timeout: Duration,
+    ) -> Self {
         PartitionedFileSink {
             path_template,
             encoding,
+            compression,
+            close_timeout,
             partitions: HashMap::new(),
+            eviction_ticker: Interval::new_interval(close_timeout),
         }
     }
 }
@@ -86,23 +115,81 @@ impl Sink for PartitionedFileSink {
 
         let path = PathBuf::from(String::from(bytes));
 
-        let mut partition = FileSink::new_with_encoding(path, self.encoding.clone());
-        partition.start_send(event)
+        let encoding = self.encoding.clone();
+        let compression = self.compression;
+        let partition = self.partitions.entry(path.clone()).or_insert_with(|| {
+            debug!(message = "opening partition", path = ?path);
+            Partition {
+                sink: FileSink::new_with_encoding_and_compression(path, encoding, compression),
+                last_used: Instant::now(),
+            }
+        });
+
+        let result = partition.sink.start_send(event);
+        match result {
+            Ok(AsyncSink::Ready) => {
+                partition.last_used = Instant::now();
+                Ok(AsyncSink::Ready)
+            }
+            Ok(AsyncSink::NotReady(event)) => Ok(AsyncSink::NotReady(event)),
+            Err(()) => {
+                // A `Sink`'s `Err` is fatal to whatever is driving it, and
+                // that's `PartitionedFileSink` itself here - if we let it
+                // propagate, one bad partition would tear down every other
+                // partition's sink along with it. Isolate the failure to
+                // just this partition instead: it's already been removed,
+                // so the event is dropped, but the sink as a whole stays up.
+                error!("Error in downstream FileSink for partition {:?}, closing it", path);
+                self.partitions.remove(&path);
+                Ok(AsyncSink::Ready)
+            }
+        }
     }
 
     fn poll_complete(&mut self) -> Result<Async<()>, Self::SinkError> {
-        self.partitions
-            .values_mut()
-            .for_each(|partition| {
-                match partition.poll_complete() {
-                    Err(err) => {
-                        error!("Error in downstream FileSink {:?}: {}", partition.path, err);
-                        //todo: close file sink
+        // Draining the ticker (rather than only checking whether it fired)
+        // re-registers this task for the next tick even when nothing else
+        // does, so the eviction scan below still runs on a timer when the
+        // sink has gone fully idle and nothing is arriving to poll it.
+        loop {
+            match self.eviction_ticker.poll() {
+                Ok(Async::Ready(_)) => continue,
+                Ok(Async::NotReady) => break,
+                Err(err) => {
+                    error!("Error in partition eviction timer: {}", err);
+                    break;
+                }
+            }
+        }
+
+        let now = Instant::now();
+        let close_timeout = self.close_timeout;
+        let mut to_evict = Vec::new();
+
+        for (path, partition) in self.partitions.iter_mut() {
+            match partition.sink.poll_complete() {
+                // Fully flushed: safe to close if it's also been idle long
+                // enough. A partition that still has buffered bytes is
+                // never evicted, even past its timeout, so we don't close
+                // over a truncated write.
+                Ok(Async::Ready(())) => {
+                    if now.duration_since(partition.last_used) > close_timeout {
+                        to_evict.push(path.clone());
                     }
-                    Ok(ok) => {},
                 }
-            });
+                Ok(Async::NotReady) => {}
+                Err(()) => {
+                    error!("Error in downstream FileSink for partition {:?}, closing it", path);
+                    to_evict.push(path.clone());
+                }
+            }
+        }
+
+        for path in to_evict {
+            debug!(message = "closing idle partition", path = ?path);
+            self.partitions.remove(&path);
+        }
 
         Ok(Async::Ready(()))
     }
-}
\ No newline at end of file
+}
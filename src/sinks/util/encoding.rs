@@ -8,27 +8,79 @@ use serde::{Deserialize, Serialize};
 pub enum BasicEncoding {
     Text,
     Json,
+    Cbor,
+    Bincode,
+    Preserves,
 }
 
+impl BasicEncoding {
+    /// `Cbor`, `Bincode` and `Preserves` produce arbitrary bytes rather than
+    /// UTF-8 text, so they can't be handed to a line-oriented sink like the
+    /// console's `LinesCodec`.
+    pub fn is_binary(&self) -> bool {
+        match self {
+            BasicEncoding::Text | BasicEncoding::Json => false,
+            BasicEncoding::Cbor | BasicEncoding::Bincode | BasicEncoding::Preserves => true,
+        }
+    }
+}
+
+// Picks the concrete encoding to use for a log event: an explicit encoding
+// always wins, otherwise we fall back to `Json` for structured events and
+// `Text` (the raw message) for unstructured ones.
+fn resolve_log_encoding(encoding: &Option<BasicEncoding>, is_structured: bool) -> BasicEncoding {
+    match encoding {
+        Some(encoding) => encoding.clone(),
+        None if is_structured => BasicEncoding::Json,
+        None => BasicEncoding::Text,
+    }
+}
+
+// Bincode isn't self-describing, so without a delimiter a reader can't tell
+// where one record ends and the next begins. Prefix each record with its
+// length so a single file can hold a stream of them.
+fn encode_length_delimited<T: Serialize>(value: &T) -> Result<Vec<u8>, ()> {
+    let body = bincode::serialize(value).map_err(|e| {
+        error!("Error encoding event as bincode: {}", e);
+    })?;
+
+    let mut framed = Vec::with_capacity(4 + body.len());
+    framed.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    framed.extend_from_slice(&body);
+    Ok(framed)
+}
+
+/// Renders an event as a line of text, for sinks that write newline-framed
+/// output (e.g. the console sink). Binary encodings are rejected by callers
+/// before events ever reach this function; see `BasicEncoding::is_binary`.
 pub fn event_as_string(event: Event, encoding: &Option<BasicEncoding>) -> Result<String, ()> {
     match event {
-        Event::Log(log) => {
-            if (log.is_structured() && encoding != &Some(BasicEncoding::Text))
-                || encoding == &Some(BasicEncoding::Json)
-            {
-                let bytes = serde_json::to_vec(&log.all_fields())
-                    .map_err(|e| panic!("Error encoding: {}", e))?;
-                String::from_utf8(bytes)
-                    .map_err(|e| panic!("Unable to convert json to utf8: {}", e))
-            } else {
+        Event::Log(log) => match resolve_log_encoding(encoding, log.is_structured()) {
+            BasicEncoding::Json => {
+                let bytes = serde_json::to_vec(&log.all_fields()).map_err(|e| {
+                    error!("Error encoding event as json: {}", e);
+                })?;
+                String::from_utf8(bytes).map_err(|e| {
+                    error!("Unable to convert json to utf8: {}", e);
+                })
+            }
+            BasicEncoding::Text => {
                 let string = log
                     .get(&event::MESSAGE)
                     .map(|v| v.to_string_lossy())
                     .unwrap_or_else(|| "".into());
                 Ok(string)
             }
-        }
-        Event::Metric(metric) => serde_json::to_string(&metric).map_err(|_| ()),
+            encoding @ BasicEncoding::Cbor
+            | encoding @ BasicEncoding::Bincode
+            | encoding @ BasicEncoding::Preserves => {
+                error!("The {:?} encoding cannot be written as text", encoding);
+                Err(())
+            }
+        },
+        Event::Metric(metric) => serde_json::to_string(&metric).map_err(|e| {
+            error!("Error encoding metric as json: {}", e);
+        }),
     }
 }
 
@@ -37,33 +89,155 @@ pub fn log_event_as_bytes(event: Event, encoding: &Option<BasicEncoding>) -> Res
 }
 
 pub fn log_event_as_bytes_with_nl(event: Event, encoding: &Option<BasicEncoding>) -> Result<Bytes, ()> {
+    let is_text = match encoding {
+        None | Some(BasicEncoding::Text) | Some(BasicEncoding::Json) => true,
+        Some(BasicEncoding::Cbor) | Some(BasicEncoding::Bincode) | Some(BasicEncoding::Preserves) => {
+            false
+        }
+    };
+
     log_event_as_raw_bytes(event, encoding).map(|mut bytes| {
-        bytes.push(b'\n');
+        // Binary encodings carry their own framing (CBOR and Preserves are
+        // self-describing, Bincode is length-delimited), so a trailing
+        // newline would just be stray bytes a reader has to skip over.
+        if is_text {
+            bytes.push(b'\n');
+        }
         Bytes::from(bytes)
     })
 }
 
 fn log_event_as_raw_bytes(event: Event, encoding: &Option<BasicEncoding>) -> Result<Vec<u8>, ()> {
-    let log = event.into_log();
+    match event {
+        Event::Log(log) => match resolve_log_encoding(encoding, log.is_structured()) {
+            BasicEncoding::Text => Ok(log
+                .get(&event::MESSAGE)
+                .map(|v| v.as_bytes().to_vec())
+                .unwrap_or_default()),
+            BasicEncoding::Json => serde_json::to_vec(&log.all_fields()).map_err(|e| {
+                error!("Error encoding event as json: {}", e);
+            }),
+            BasicEncoding::Cbor => serde_cbor::to_vec(&log.all_fields()).map_err(|e| {
+                error!("Error encoding event as cbor: {}", e);
+            }),
+            BasicEncoding::Bincode => encode_length_delimited(&log.all_fields()),
+            BasicEncoding::Preserves => log_fields_as_preserves(&log),
+        },
+        Event::Metric(metric) => match resolve_log_encoding(encoding, true) {
+            BasicEncoding::Text => {
+                error!("The text encoding does not support metric events");
+                Err(())
+            }
+            BasicEncoding::Json => serde_json::to_vec(&metric).map_err(|e| {
+                error!("Error encoding metric as json: {}", e);
+            }),
+            BasicEncoding::Cbor => serde_cbor::to_vec(&metric).map_err(|e| {
+                error!("Error encoding metric as cbor: {}", e);
+            }),
+            BasicEncoding::Bincode => encode_length_delimited(&metric),
+            BasicEncoding::Preserves => metric_as_preserves(&metric),
+        },
+    }
+}
+
+// `preserves::value::serde` would let serde's `Serialize` impl pick the
+// Preserves shape for us, but serde has no concept of a labeled record, so
+// it can't produce one - it would emit every metric as a plain dictionary.
+// Building the
+// `Value` tree by hand instead lets us keep that information: log fields
+// become a dictionary with byte-valued fields encoded as byte strings, and
+// metrics become a record tagged with their variant name, e.g. `<counter
+// name val>`.
+//
+// `serde_cbor::Value` is used as the intermediate step because, unlike
+// `serde_json::Value`, its data model already distinguishes bytes from text,
+// so nothing is lost translating an event's fields into it first.
+fn cbor_value_to_preserves(value: serde_cbor::Value) -> preserves::value::Value {
+    use preserves::value::Value as PValue;
 
-    match (encoding, log.is_structured()) {
-        (&Some(BasicEncoding::Json), _) | (_, true) => {
-            serde_json::to_vec(&log.all_fields()).map_err(|e| panic!("Error encoding: {}", e))
+    match value {
+        serde_cbor::Value::Null => PValue::symbol("null".to_string()),
+        serde_cbor::Value::Bool(b) => PValue::boolean(b),
+        serde_cbor::Value::Integer(i) => PValue::signed_integer(i as i64),
+        // `serde_cbor::Value::Float` carries an `f64`; Preserves
+        // distinguishes single- from double-precision, so this has to go
+        // through `double` rather than `float` or it silently narrows every
+        // float field to f32 on the way out.
+        serde_cbor::Value::Float(f) => PValue::double(f),
+        serde_cbor::Value::Bytes(bytes) => PValue::byte_string(bytes),
+        serde_cbor::Value::Text(text) => PValue::text(text),
+        serde_cbor::Value::Array(items) => {
+            PValue::sequence(items.into_iter().map(cbor_value_to_preserves).collect())
         }
+        serde_cbor::Value::Map(map) => PValue::dictionary(
+            map.into_iter()
+                .map(|(k, v)| (cbor_value_to_preserves(k), cbor_value_to_preserves(v)))
+                .collect(),
+        ),
+        serde_cbor::Value::Tag(_, inner) => cbor_value_to_preserves(*inner),
+        _ => PValue::symbol("unsupported".to_string()),
+    }
+}
 
-        (&Some(BasicEncoding::Text), _) | (_, false) => {
-            let bytes = log
-                .get(&event::MESSAGE)
-                .map(|v| v.as_bytes().to_vec())
-                .unwrap_or(Vec::new());
-            Ok(bytes)
+fn log_fields_as_preserves(log: &event::LogEvent) -> Result<Vec<u8>, ()> {
+    let fields = serde_cbor::value::to_value(log.all_fields()).map_err(|e| {
+        error!("Error encoding event as preserves: {}", e);
+    })?;
+
+    preserves::value::to_bytes(&cbor_value_to_preserves(fields)).map_err(|e| {
+        error!("Error encoding event as preserves: {}", e);
+    })
+}
+
+// Metrics serialize (e.g. to json/cbor) as an internally-tagged struct, a
+// map with a "type" field alongside the variant's own fields. We pull that
+// tag out and use it as the record's label rather than letting it ride along
+// as just another dictionary entry.
+fn metric_as_preserves(metric: &event::Metric) -> Result<Vec<u8>, ()> {
+    let tagged = serde_cbor::value::to_value(metric).map_err(|e| {
+        error!("Error encoding metric as preserves: {}", e);
+    })?;
+
+    let mut fields = match tagged {
+        serde_cbor::Value::Map(fields) => fields,
+        _ => {
+            error!("Error encoding metric as preserves: expected a tagged struct");
+            return Err(());
+        }
+    };
+
+    let label = match fields.remove(&serde_cbor::Value::Text("type".to_string())) {
+        Some(serde_cbor::Value::Text(label)) => label,
+        _ => {
+            error!("Error encoding metric as preserves: metric is missing its \"type\" tag");
+            return Err(());
+        }
+    };
+
+    // Preserves records are positional, so the order these fields go in
+    // matters. `name` and `val` are common to every metric variant and
+    // always lead, in that order; anything else a variant adds follows in
+    // the map's key order, which for `serde_cbor::Value` keys is canonical
+    // CBOR order (shorter names first, then by name). `name` and `val` are
+    // placed explicitly because that key order alone would put `val` first.
+    let mut record_fields = Vec::with_capacity(fields.len());
+    for key in &["name", "val"] {
+        if let Some(value) = fields.remove(&serde_cbor::Value::Text((*key).to_string())) {
+            record_fields.push(cbor_value_to_preserves(value));
         }
     }
+    record_fields.extend(fields.into_iter().map(|(_, value)| cbor_value_to_preserves(value)));
+
+    let value = preserves::value::Value::record(preserves::value::Value::symbol(label), record_fields);
+
+    preserves::value::to_bytes(&value).map_err(|e| {
+        error!("Error encoding metric as preserves: {}", e);
+    })
 }
 
 #[cfg(test)]
 mod test {
-    use super::event_as_string;
+    use super::{event_as_string, log_event_as_bytes, BasicEncoding};
     use crate::{event::Metric, Event};
 
     #[test]
@@ -83,4 +257,67 @@ mod test {
             event_as_string(event, &None)
         );
     }
+
+    #[test]
+    fn rejects_binary_encoding_as_text() {
+        let event = Event::from("foo");
+        assert_eq!(
+            Err(()),
+            event_as_string(event, &Some(BasicEncoding::Cbor))
+        );
+    }
+
+    #[test]
+    fn cbor_roundtrips_log_fields() {
+        let event = Event::from("foo");
+        let bytes = log_event_as_bytes(event, &Some(BasicEncoding::Cbor)).unwrap();
+        let fields: std::collections::BTreeMap<String, String> =
+            serde_cbor::from_slice(&bytes).unwrap();
+        assert_eq!(fields.get("message").map(String::as_str), Some("foo"));
+    }
+
+    #[test]
+    fn bincode_frames_are_length_prefixed() {
+        let event = Event::from("foo");
+        let bytes = log_event_as_bytes(event, &Some(BasicEncoding::Bincode)).unwrap();
+        let len = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+        assert_eq!(len, bytes.len() - 4);
+    }
+
+    #[test]
+    fn rejects_preserves_encoding_as_text() {
+        let event = Event::from("foo");
+        assert_eq!(
+            Err(()),
+            event_as_string(event, &Some(BasicEncoding::Preserves))
+        );
+    }
+
+    #[test]
+    fn preserves_roundtrips_log_fields_as_a_dictionary() {
+        let event = Event::from("foo");
+        let bytes = log_event_as_bytes(event, &Some(BasicEncoding::Preserves)).unwrap();
+
+        let value = preserves::value::from_bytes(&bytes).expect("valid preserves binary");
+        let fields = value
+            .as_dictionary()
+            .expect("log fields encode as a dictionary");
+        let message = fields
+            .get(&preserves::value::Value::text("message".to_string()))
+            .expect("dictionary has a message field");
+        assert_eq!(message.as_text(), Some("foo"));
+    }
+
+    #[test]
+    fn preserves_encodes_metrics_as_a_labeled_record() {
+        let event = Event::Metric(Metric::Counter {
+            name: "foos".into(),
+            val: 100.0,
+        });
+        let bytes = log_event_as_bytes(event, &Some(BasicEncoding::Preserves)).unwrap();
+
+        let value = preserves::value::from_bytes(&bytes).expect("valid preserves binary");
+        let record = value.as_record().expect("metric encodes as a record");
+        assert_eq!(record.label().as_symbol(), Some("counter"));
+    }
 }